@@ -0,0 +1,258 @@
+use std::cmp::Ordering;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::Path;
+use std::ptr;
+use std::sync::Arc;
+
+use libsqlite3_sys::{
+    sqlite3, sqlite3_busy_timeout, sqlite3_create_collation_v2, sqlite3_enable_load_extension,
+    sqlite3_errmsg, sqlite3_exec, sqlite3_free, sqlite3_load_extension, SQLITE_OK, SQLITE_UTF8,
+};
+
+use crate::error::Error;
+use crate::SqliteConnectOptions;
+
+type CollationFn = dyn Fn(&str, &str) -> Ordering + Send + Sync;
+
+/// Applies the parts of [`SqliteConnectOptions`] that can only take effect once a raw `sqlite3`
+/// handle is open (the SQLCipher key, `busy_timeout`, extension loading, custom collations).
+///
+/// This is invoked by the connection-establishment path immediately after `sqlite3_open_v2`
+/// returns, before any other statement runs. In particular the SQLCipher key must be the very
+/// first statement executed against the handle, since SQLCipher needs it before it can read the
+/// page cache.
+pub(crate) unsafe fn apply_connect_options(
+    handle: *mut sqlite3,
+    options: &SqliteConnectOptions,
+) -> Result<(), Error> {
+    if let Some(key) = &options.key {
+        exec(handle, &key_pragma(key))?;
+    }
+
+    // `0` disables the busy handler, which is also `sqlite3_busy_timeout`'s own default, so this
+    // is safe to call unconditionally rather than only when `options.busy_timeout` is `Some`.
+    let millis = options
+        .busy_timeout
+        .map_or(0, |timeout| c_int::try_from(timeout.as_millis()).unwrap_or(c_int::MAX));
+    sqlite3_busy_timeout(handle, millis);
+
+    load_extensions(handle, options)?;
+    register_collations(handle, options)?;
+
+    Ok(())
+}
+
+/// Builds the `PRAGMA key = ...` statement for `key` (already either a passphrase or, for
+/// `hexkey`-derived keys, an `x'...'` raw-key literal).
+///
+/// `PRAGMA` doesn't support bound parameters, so the key has to be interpolated into the SQL
+/// text; embedded quotes are escaped by doubling them (standard SQLite string-literal escaping)
+/// so a passphrase containing a quote can't break out of the literal.
+fn key_pragma(key: &str) -> String {
+    match key.strip_prefix("x'") {
+        Some(hex) => format!("PRAGMA key = \"x'{}\"", hex.replace('"', "\"\"")),
+        None => format!("PRAGMA key = '{}'", key.replace('\'', "''")),
+    }
+}
+
+/// Registers every collation added via `SqliteConnectOptions::collation` with SQLite, so
+/// `COLLATE name` can be used in queries on this connection right away.
+unsafe fn register_collations(handle: *mut sqlite3, options: &SqliteConnectOptions) -> Result<(), Error> {
+    for (name, compare) in &options.collations {
+        let name_c = CString::new(name.as_str()).map_err(|_| {
+            Error::Configuration(format!("collation name {name:?} contains a NUL byte").into())
+        })?;
+
+        // `sqlite3_create_collation_v2` only accepts a thin `*mut c_void`, so box the `Arc`
+        // (itself a fat pointer, since `CollationFn` is unsized) one more time before handing
+        // it over. `collation_destroy` reclaims this box when SQLite drops the collation.
+        let user_data = Box::into_raw(Box::new(Arc::clone(compare))) as *mut c_void;
+
+        let rc = sqlite3_create_collation_v2(
+            handle,
+            name_c.as_ptr(),
+            SQLITE_UTF8,
+            user_data,
+            Some(collation_compare),
+            Some(collation_destroy),
+        );
+
+        if rc != SQLITE_OK {
+            // SQLite didn't take ownership of `user_data` on failure; reclaim it ourselves.
+            drop(Box::from_raw(user_data as *mut Arc<CollationFn>));
+            return Err(db_error(handle, rc));
+        }
+    }
+
+    Ok(())
+}
+
+extern "C" fn collation_compare(
+    user_data: *mut c_void,
+    len1: c_int,
+    text1: *const c_void,
+    len2: c_int,
+    text2: *const c_void,
+) -> c_int {
+    // A panic unwinding across this `extern "C"` boundary would abort the whole process, so
+    // catch it here and fall back to a safe default ordering instead — mirroring rusqlite's
+    // `collation.rs`, which guards its comparator callback the same way.
+    let result = std::panic::catch_unwind(|| unsafe {
+        let compare = &*(user_data as *const Arc<CollationFn>);
+
+        let a = std::slice::from_raw_parts(text1 as *const u8, len1 as usize);
+        let b = std::slice::from_raw_parts(text2 as *const u8, len2 as usize);
+
+        compare(&String::from_utf8_lossy(a), &String::from_utf8_lossy(b))
+    });
+
+    match result {
+        Ok(Ordering::Less) => -1,
+        Ok(Ordering::Equal) => 0,
+        Ok(Ordering::Greater) => 1,
+        Err(_) => 0,
+    }
+}
+
+extern "C" fn collation_destroy(user_data: *mut c_void) {
+    unsafe {
+        drop(Box::from_raw(user_data as *mut Arc<CollationFn>));
+    }
+}
+
+/// Loads every extension named in `options`, then always restores extension loading to
+/// disabled, even if one of them failed to load, so the connection is never left in a state
+/// where arbitrary SQL could load a library.
+unsafe fn load_extensions(handle: *mut sqlite3, options: &SqliteConnectOptions) -> Result<(), Error> {
+    if options.extensions.is_empty() {
+        return Ok(());
+    }
+
+    let rc = sqlite3_enable_load_extension(handle, 1);
+    if rc != SQLITE_OK {
+        return Err(db_error(handle, rc));
+    }
+
+    let result = load_each_extension(handle, options);
+
+    let disable_rc = sqlite3_enable_load_extension(handle, 0);
+
+    result.and_then(|()| {
+        if disable_rc != SQLITE_OK {
+            Err(db_error(handle, disable_rc))
+        } else {
+            Ok(())
+        }
+    })
+}
+
+unsafe fn load_each_extension(handle: *mut sqlite3, options: &SqliteConnectOptions) -> Result<(), Error> {
+    for (path, entry_point) in &options.extensions {
+        let path_c = path_to_cstring(path)?;
+        let entry_point_c = entry_point
+            .as_deref()
+            .map(CString::new)
+            .transpose()
+            .map_err(|_| {
+                Error::Configuration(
+                    format!(
+                        "entry point for extension {:?} contains a NUL byte",
+                        path.display()
+                    )
+                    .into(),
+                )
+            })?;
+
+        let mut errmsg: *mut c_char = ptr::null_mut();
+        let rc = sqlite3_load_extension(
+            handle,
+            path_c.as_ptr(),
+            entry_point_c.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            &mut errmsg,
+        );
+
+        if rc != SQLITE_OK {
+            let message = if errmsg.is_null() {
+                self::errmsg(handle)
+            } else {
+                let message = CStr::from_ptr(errmsg).to_string_lossy().into_owned();
+                sqlite3_free(errmsg as *mut c_void);
+                message
+            };
+
+            return Err(Error::Configuration(
+                format!("failed to load extension {:?}: {message}", path.display()).into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn path_to_cstring(path: &Path) -> Result<CString, Error> {
+    CString::new(path.as_os_str().as_encoded_bytes()).map_err(|_| {
+        Error::Configuration(format!("extension path {:?} contains a NUL byte", path).into())
+    })
+}
+
+unsafe fn exec(handle: *mut sqlite3, sql: &str) -> Result<(), Error> {
+    let sql = CString::new(sql)
+        .map_err(|_| Error::Configuration("SQL statement contains a NUL byte".into()))?;
+
+    let rc = sqlite3_exec(handle, sql.as_ptr(), None, ptr::null_mut(), ptr::null_mut());
+
+    if rc != SQLITE_OK {
+        return Err(db_error(handle, rc));
+    }
+
+    Ok(())
+}
+
+unsafe fn db_error(handle: *mut sqlite3, code: c_int) -> Error {
+    let message = errmsg(handle);
+    Error::Configuration(format!("sqlite error {code}: {message}").into())
+}
+
+unsafe fn errmsg(handle: *mut sqlite3) -> String {
+    let ptr: *const c_char = sqlite3_errmsg(handle);
+    if ptr.is_null() {
+        return String::new();
+    }
+
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::key_pragma;
+
+    #[test]
+    fn test_collation_compare_panic_falls_back_to_equal() {
+        let compare: Arc<super::CollationFn> = Arc::new(|_: &str, _: &str| panic!("boom"));
+        let user_data = Box::into_raw(Box::new(compare)) as *mut std::ffi::c_void;
+
+        let result = super::collation_compare(
+            user_data,
+            1,
+            b"a".as_ptr() as *const std::ffi::c_void,
+            1,
+            b"b".as_ptr() as *const std::ffi::c_void,
+        );
+        assert_eq!(result, 0);
+
+        unsafe { drop(Box::from_raw(user_data as *mut Arc<super::CollationFn>)) };
+    }
+
+    #[test]
+    fn test_key_pragma_escapes_embedded_quotes() {
+        assert_eq!(key_pragma("o'brien"), "PRAGMA key = 'o''brien'");
+        assert_eq!(key_pragma("plain-passphrase"), "PRAGMA key = 'plain-passphrase'");
+        assert_eq!(
+            key_pragma("x'2DD2\"9CA8'"),
+            "PRAGMA key = \"x'2DD2\"\"9CA8'\""
+        );
+    }
+}
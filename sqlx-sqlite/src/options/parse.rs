@@ -1,7 +1,10 @@
 use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use percent_encoding::{percent_decode_str, percent_encode, AsciiSet};
 use url::Url;
@@ -13,6 +16,25 @@ use crate::SqliteConnectOptions;
 
 static IN_MEMORY_DB_SEQ: AtomicUsize = AtomicUsize::new(0);
 
+const PRAGMA_PREFIX: &str = "pragma_";
+
+/// Parses a millisecond `busy_timeout` value shared by the `busy_timeout` and legacy
+/// `pragma_busy_timeout` query parameters. `0` disables the busy handler.
+fn parse_busy_timeout_millis(param: &str, value: &str) -> Result<Option<Duration>, Error> {
+    let millis: u64 = value.parse().map_err(|_| {
+        Error::Configuration(
+            format!("expected an integer number of milliseconds for `{param}`; got {value:?}")
+                .into(),
+        )
+    })?;
+
+    Ok(if millis == 0 {
+        None
+    } else {
+        Some(Duration::from_millis(millis))
+    })
+}
+
 impl SqliteConnectOptions {
     pub(crate) fn from_db_and_params(database: &str, params: Option<&str>) -> Result<Self, Error> {
         let mut options = Self::default();
@@ -20,7 +42,7 @@ impl SqliteConnectOptions {
         if database == ":memory:" {
             options.in_memory = true;
             options.shared_cache = true;
-            let seqno = IN_MEMORY_DB_SEQ.fetch_add(1, Ordering::Relaxed);
+            let seqno = IN_MEMORY_DB_SEQ.fetch_add(1, AtomicOrdering::Relaxed);
             options.filename = Cow::Owned(PathBuf::from(format!("file:sqlx-in-memory-{seqno}")));
         } else {
             // % decode to allow for `?` or `#` in the filename
@@ -102,83 +124,61 @@ impl SqliteConnectOptions {
 
                     "vfs" => options.vfs = Some(Cow::Owned(value.into_owned())),
 
+                    // Enables opening SQLCipher-encrypted databases directly from a connection
+                    // URL. The key is applied via `PRAGMA key` as the very first statement once
+                    // the handle is open, before any other query touches the database pages.
+                    "key" => {
+                        options.key = Some(Cow::Owned(value.into_owned()));
+                    }
+
+                    // Like `key`, but the value is a raw key already encoded as hexadecimal,
+                    // wrapped in SQLCipher's `x'...'` raw-key literal syntax.
+                    "hexkey" => {
+                        options.key = Some(Cow::Owned(format!("x'{value}'")));
+                    }
+
+                    // May be repeated to request that one or more loadable extensions be opened
+                    // against this connection, mirroring `SqliteConnectOptions::extension`. An
+                    // optional entry point symbol can be supplied after a comma, e.g.
+                    // `extension=./mod_spatialite,sqlite3_modspatialite_init`.
+                    "extension" => {
+                        let value = value.into_owned();
+                        let (path, entry_point) = match value.split_once(',') {
+                            Some((path, entry_point)) => (path, Some(entry_point.to_owned())),
+                            None => (&*value, None),
+                        };
+                        options.extensions.push((PathBuf::from(path), entry_point));
+                    }
+
+                    // Dedicated, typed alternative to `pragma_busy_timeout`: on connect, this is
+                    // passed straight to `sqlite3_busy_timeout` so that `SQLITE_BUSY` from
+                    // concurrent writers is retried up to the given bound instead of failing
+                    // immediately. A value of `0` disables the busy handler.
+                    "busy_timeout" => {
+                        options.busy_timeout = parse_busy_timeout_millis("busy_timeout", &value)?;
+                    }
+
                     // References https://www.sqlite.org/pragma.html
-                    "pragma_analysis_limit" | 
-                    "pragma_application_id" | 
-                    "pragma_auto_vacuum" | 
-                    "pragma_automatic_index" | 
-                    "pragma_busy_timeout" | 
-                    "pragma_cache_size" | 
-                    "pragma_cache_spill" | 
-                    "pragma_case_sensitive_like" | 
-                    "pragma_cell_size_check" | 
-                    "pragma_checkpoint_fullfsync" | 
-                    "pragma_collation_list" | 
-                    "pragma_compile_options" | 
-                    "pragma_count_changes" | 
-                    "pragma_data_store_directory" | 
-                    "pragma_data_version" | 
-                    "pragma_database_list" | 
-                    "pragma_default_cache_size" | 
-                    "pragma_defer_foreign_keys" | 
-                    "pragma_empty_result_callbacks" | 
-                    "pragma_encoding" | 
-                    "pragma_foreign_key_check" | 
-                    "pragma_foreign_key_list" | 
-                    "pragma_foreign_keys" | 
-                    "pragma_freelist_count" | 
-                    "pragma_full_column_names" | 
-                    "pragma_fullfsync" | 
-                    "pragma_function_list" | 
-                    "pragma_hard_heap_limit" | 
-                    "pragma_ignore_check_constraints" | 
-                    "pragma_incremental_vacuum" | 
-                    "pragma_index_info" | 
-                    "pragma_index_list" | 
-                    "pragma_index_xinfo" | 
-                    "pragma_integrity_check" | 
-                    "pragma_journal_mode" | 
-                    "pragma_journal_size_limit" | 
-                    "pragma_legacy_alter_table" | 
-                    "pragma_legacy_file_format" | 
-                    "pragma_locking_mode" | 
-                    "pragma_max_page_count" | 
-                    "pragma_mmap_size" | 
-                    "pragma_module_list" | 
-                    "pragma_optimize" | 
-                    "pragma_page_count" | 
-                    "pragma_page_size" | 
-                    "pragma_parser_trace" | 
-                    "pragma_pragma_list" | 
-                    "pragma_query_only" | 
-                    "pragma_quick_check" | 
-                    "pragma_read_uncommitted" | 
-                    "pragma_recursive_triggers" | 
-                    "pragma_reverse_unordered_selects" | 
-                    "pragma_schema_version" | 
-                    "pragma_secure_delete" | 
-                    "pragma_short_column_names" | 
-                    "pragma_shrink_memory" | 
-                    "pragma_soft_heap_limit" | 
-                    "pragma_stats" | 
-                    "pragma_synchronous" | 
-                    "pragma_table_info" | 
-                    "pragma_table_list" | 
-                    "pragma_table_xinfo" | 
-                    "pragma_temp_store" | 
-                    "pragma_temp_store_directory" | 
-                    "pragma_threads" | 
-                    "pragma_trusted_schema" | 
-                    "pragma_user_version" | 
-                    "pragma_vdbe_addoptrace" | 
-                    "pragma_vdbe_debug" | 
-                    "pragma_vdbe_listing" | 
-                    "pragma_vdbe_trace" | 
-                    "pragma_wal_autocheckpoint" | 
-                    "pragma_wal_checkpoint" | 
-                    "pragma_writable_schema" => {
-                        options = options.pragma(key.into_owned().replace("pragma_", ""), Cow::Owned(value.into_owned()));
-                    },
+                    //
+                    // Rather than matching against a hand-maintained list of pragma names (which
+                    // goes stale every time SQLite adds one), accept any `pragma_<name>` key here
+                    // and pass it through to the opened connection. Names are not checked against
+                    // `pragma_list`: that's itself a pragma, so checking against it would require
+                    // a live connection, which doesn't exist at URL-parse time. SQLite ignores a
+                    // pragma it doesn't recognise without reporting an error, so a misspelled
+                    // name is accepted silently.
+                    //
+                    // `pragma_busy_timeout` predates the typed `busy_timeout` parameter above;
+                    // keep accepting it for existing connection strings and translate it onto
+                    // the same field rather than breaking anyone already relying on it.
+                    "pragma_busy_timeout" => {
+                        options.busy_timeout = parse_busy_timeout_millis("pragma_busy_timeout", &value)?;
+                    }
+
+                    _ if key.starts_with("pragma_") => {
+                        let pragma_name = key[PRAGMA_PREFIX.len()..].to_string();
+                        options = options.pragma(pragma_name, Cow::Owned(value.into_owned()));
+                    }
 
                     _ => {
                         return Err(Error::Configuration(
@@ -236,8 +236,56 @@ impl SqliteConnectOptions {
             url.query_pairs_mut().append_pair("vfs", vfs);
         }
 
+        if let Some(busy_timeout) = self.busy_timeout {
+            url.query_pairs_mut()
+                .append_pair("busy_timeout", &busy_timeout.as_millis().to_string());
+        }
+
+        for (key, value) in &self.pragmas {
+            // SQLCipher key pragmas are secrets too; see the note on `key` below.
+            if matches!(&**key, "key" | "hexkey" | "rekey" | "hexrekey") {
+                continue;
+            }
+
+            if let Some(value) = value {
+                url.query_pairs_mut()
+                    .append_pair(&format!("{PRAGMA_PREFIX}{key}"), value);
+            }
+        }
+
+        for (path, entry_point) in &self.extensions {
+            let value = match entry_point {
+                Some(entry_point) => format!("{},{entry_point}", path.display()),
+                None => path.display().to_string(),
+            };
+            url.query_pairs_mut().append_pair("extension", &value);
+        }
+
+        // Never round-trip the encryption key: it must not leak into a logged or persisted
+        // connection string.
+
         url
     }
+
+    /// Register a custom collation that will be available on every connection opened from
+    /// these options, mirroring rusqlite's `Connection::create_collation`.
+    ///
+    /// `compare` is registered with SQLite via `sqlite3_create_collation_v2` during connection
+    /// establishment (after the handle opens, before any user query runs), so it can be used in
+    /// `ORDER BY col COLLATE name` or `CREATE TABLE ... COLLATE name` right away. Registering
+    /// here, rather than per-connection, means every connection the pool opens behaves
+    /// consistently without the caller having to hook `after_connect`.
+    ///
+    /// Registration failures surface as a connection error the next time these options are
+    /// used to open a connection.
+    pub fn collation<N, F>(mut self, name: N, compare: F) -> Self
+    where
+        N: Into<String>,
+        F: Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+    {
+        self.collations.push((name.into(), Arc::new(compare)));
+        self
+    }
 }
 
 impl FromStr for SqliteConnectOptions {
@@ -297,6 +345,97 @@ fn test_parse_shared_in_memory() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_parse_and_redact_key() -> Result<(), Error> {
+    let options: SqliteConnectOptions = "sqlite://a.db?key=supersecret".parse()?;
+    assert_eq!(options.key.as_deref(), Some("supersecret"));
+
+    let url = options.build_url();
+    assert!(!url.as_str().contains("supersecret"));
+    assert!(!url.as_str().contains("key="));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_and_roundtrip_extensions() -> Result<(), Error> {
+    let url = "sqlite://a.db?extension=./mod_spatialite&extension=./fts5,sqlite3_fts5_init";
+    let options: SqliteConnectOptions = url.parse()?;
+    assert_eq!(
+        options.extensions,
+        vec![
+            (PathBuf::from("./mod_spatialite"), None),
+            (
+                PathBuf::from("./fts5"),
+                Some("sqlite3_fts5_init".to_string())
+            ),
+        ]
+    );
+
+    let reparsed: SqliteConnectOptions = options.build_url().as_str().parse()?;
+    assert_eq!(reparsed.extensions, options.extensions);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_and_roundtrip_pragmas() -> Result<(), Error> {
+    let url = "sqlite://a.db?pragma_journal_mode=WAL&pragma_synchronous=NORMAL&pragma_cache_size=-2000";
+    let options: SqliteConnectOptions = url.parse()?;
+
+    let reparsed: SqliteConnectOptions = options.build_url().as_str().parse()?;
+    assert_eq!(reparsed.pragmas, options.pragmas);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_busy_timeout() -> Result<(), Error> {
+    let options: SqliteConnectOptions = "sqlite://a.db?busy_timeout=2500".parse()?;
+    assert_eq!(options.busy_timeout, Some(std::time::Duration::from_millis(2500)));
+    assert!(options.build_url().as_str().contains("busy_timeout=2500"));
+
+    let options: SqliteConnectOptions = "sqlite://a.db?busy_timeout=0".parse()?;
+    assert_eq!(options.busy_timeout, None);
+
+    let err = "sqlite://a.db?busy_timeout=nope"
+        .parse::<SqliteConnectOptions>()
+        .unwrap_err();
+    assert!(matches!(err, Error::Configuration(_)));
+
+    Ok(())
+}
+
+#[test]
+fn test_legacy_pragma_busy_timeout_is_translated() -> Result<(), Error> {
+    let options: SqliteConnectOptions = "sqlite://a.db?pragma_busy_timeout=2500".parse()?;
+    assert_eq!(options.busy_timeout, Some(Duration::from_millis(2500)));
+    assert!(options.pragmas.get("busy_timeout").is_none());
+
+    let options: SqliteConnectOptions = "sqlite://a.db?pragma_busy_timeout=0".parse()?;
+    assert_eq!(options.busy_timeout, None);
+
+    let err = "sqlite://a.db?pragma_busy_timeout=nope"
+        .parse::<SqliteConnectOptions>()
+        .unwrap_err();
+    assert!(matches!(err, Error::Configuration(_)));
+
+    Ok(())
+}
+
+#[test]
+fn test_collation_stores_comparator_by_name() {
+    let options = SqliteConnectOptions::default()
+        .collation("nocase_unicode", |a, b| {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        });
+
+    assert_eq!(options.collations.len(), 1);
+    assert_eq!(options.collations[0].0, "nocase_unicode");
+    assert_eq!((options.collations[0].1)("A", "a"), Ordering::Equal);
+    assert_eq!((options.collations[0].1)("a", "b"), Ordering::Less);
+}
+
 #[test]
 fn it_returns_the_parsed_url() -> Result<(), Error> {
     let url = "sqlite://test.db?mode=rw&cache=shared";